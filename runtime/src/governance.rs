@@ -7,7 +7,7 @@ use support::{
 use system::ensure_signed;
 use codec::{Encode, Decode};
 use rstd::prelude::Vec;
-use sr_primitives::traits::{Hash, CheckedAdd, SaturatedConversion};
+use sr_primitives::traits::{Hash, CheckedAdd, SaturatedConversion, Saturating, Bounded};
 
 // Option: {title: String, pot: u64, voters: <Vec:T::AccountId>}
 // Voter: {accountId, votedVotes:<Vec: u64>, timeLastVoted: timestamp, balance: balances}
@@ -21,6 +21,45 @@ pub struct Vote<AccountId, BlockNumber> {
     vote_ends: BlockNumber,
     concluded: bool,
     vote_type: u8,
+    threshold: VoteThreshold,
+}
+
+// Adaptive quorum biasing, as used by Substrate's democracy pallet: the
+// passage rule a vote is checked against once it concludes.
+#[derive(Clone, Copy, PartialEq, Eq, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum VoteThreshold {
+    SuperMajorityApprove,
+    SuperMajorityAgainst,
+    SimpleMajority,
+}
+
+impl Default for VoteThreshold {
+    fn default() -> Self {
+        VoteThreshold::SimpleMajority
+    }
+}
+
+// n1/d1 < n2/d2, computed as n1*d2 < n2*d1 in u128 to dodge overflow.
+fn compare_rationals(n1: u128, d1: u128, n2: u128, d2: u128) -> bool {
+    if d1 == 0 || d2 == 0 {
+        return false;
+    }
+    n1 * d2 < n2 * d1
+}
+
+// Integer square root via Newton's method.
+fn isqrt(n: u128) -> u128 {
+    if n == 0 {
+        return 0;
+    }
+    let mut x = n;
+    let mut y = (x + 1) / 2;
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Encode, Decode)]
@@ -30,15 +69,75 @@ pub enum Ballot {
     Nay,
 }
 
+// Bounded conviction levels for lock voting, modelled on the exponential
+// Lockout schedule used by Solana's vote_state: each step doubles the lock
+// period instead of letting the voter pick an arbitrary duration.
+#[derive(Clone, Copy, PartialEq, Eq, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum Conviction {
+    None,
+    Locked1x,
+    Locked2x,
+    Locked3x,
+    Locked4x,
+    Locked5x,
+    Locked6x,
+}
+
+impl Default for Conviction {
+    fn default() -> Self {
+        Conviction::None
+    }
+}
+
+impl Conviction {
+    // Weight multiplier applied to the locked deposit when tallying votes.
+    fn weight_multiplier(self) -> u64 {
+        match self {
+            Conviction::None => 0,
+            Conviction::Locked1x => 1,
+            Conviction::Locked2x => 2,
+            Conviction::Locked3x => 3,
+            Conviction::Locked4x => 4,
+            Conviction::Locked5x => 5,
+            Conviction::Locked6x => 6,
+        }
+    }
+
+    // Number of LockPeriods to lock for: LockPeriod * 2^(c-1), zero for `None`.
+    fn lock_periods(self) -> u32 {
+        match self {
+            Conviction::None => 0,
+            Conviction::Locked1x => 1,
+            Conviction::Locked2x => 2,
+            Conviction::Locked3x => 4,
+            Conviction::Locked4x => 8,
+            Conviction::Locked5x => 16,
+            Conviction::Locked6x => 32,
+        }
+    }
+}
+
+// Anti-flip lockout for a single (vote, voter) pair: how many times the ballot
+// has been flipped, and the block before which another flip is rejected.
+#[derive(PartialEq, Encode, Decode, Default)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct FlipLock<BlockNumber> {
+    flip_count: u32,
+    locked_until: BlockNumber,
+}
+
 #[derive(PartialEq, Encode, Decode, Default)]
 #[cfg_attr(feature = "std", derive(Debug))]
 pub struct LockInfo<Balance, BlockNumber> {
     deposit: Balance,
     duration: BlockNumber,
-    until: BlockNumber
+    until: BlockNumber,
+    conviction: Conviction,
 }
 
 pub type ReferenceIndex = u64;
+const DELEGATE_LOCK_ID: LockIdentifier = *b"govdeleg";
 pub type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
 
 // import Trait from balances, timestamp, event
@@ -46,15 +145,25 @@ pub trait Trait: balances::Trait + system::Trait {
     type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
     type Currency: LockableCurrency<Self::AccountId, Moment=Self::BlockNumber>;
     type LockPeriod: Get<Self::BlockNumber>;
+    // base lockout applied after a ballot flip, doubled per further flip (see FlipLock)
+    type BaseFlipLockout: Get<Self::BlockNumber>;
+    // epoch-credits-style participation history, capped per account like Solana's MAX_EPOCH_CREDITS_HISTORY
+    type MaxCreditHistory: Get<usize>;
+    // reward pot claim_rewards pays out of, and the reward paid per accumulated credit
+    type RewardPot: Get<Self::AccountId>;
+    type CreditRewardRate: Get<BalanceOf<Self>>;
 }
 
 decl_event!(
-	pub enum Event<T> where AccountId = <T as system::Trait>::AccountId {
+	pub enum Event<T> where AccountId = <T as system::Trait>::AccountId, Balance = BalanceOf<T> {
         //created, voted, withdrawn, finalized
         Created(AccountId, u64),
         Voted(AccountId, u64, Ballot),
-        Concluded(u64),
+        Concluded(u64, u64, u64, bool),
         Withdrew(AccountId, ReferenceIndex),
+        Delegated(AccountId, AccountId),
+        Undelegated(AccountId),
+        RewardsClaimed(AccountId, Balance),
 	}
 );
 
@@ -83,6 +192,21 @@ decl_storage! {
 
         LockBalance: map (ReferenceIndex, T::AccountId) => LockInfo<BalanceOf<T>, T::BlockNumber>;
         LockCount get(lock_count): u64;
+
+        // Electorate for type-0 (unweighted) votes: everyone who has ever cast a ballot or delegated.
+        KnownVoters: map T::AccountId => bool;
+        TotalVoterCount get(total_voter_count): u64;
+
+        // delegator => (delegate, conviction, locked balance)
+        Delegations: map T::AccountId => (T::AccountId, Conviction, BalanceOf<T>);
+        // delegate => [delegator, ...], the reverse index tally walks
+        Delegators get(delegators_of): map T::AccountId => Vec<T::AccountId>;
+
+        // rolling (block, credits earned that block) history, one credit per concluded
+        // vote participated in, capped to T::MaxCreditHistory like Solana's epoch credits
+        VoterCredits get(credits_of): map T::AccountId => Vec<(T::BlockNumber, u64)>;
+
+        BallotLockout: map (ReferenceIndex, T::AccountId) => FlipLock<T::BlockNumber>;
     }
 }
 
@@ -93,7 +217,7 @@ decl_module! {
         // Creator Modules
         // Create a new vote
         // TODO: Takes expiring time, title as data: Vec, voting_type
-        pub fn create_vote(origin, vote_type:u8, exp_length: T::BlockNumber ,data: Vec<u8>) -> Result {
+        pub fn create_vote(origin, vote_type:u8, exp_length: T::BlockNumber, threshold: VoteThreshold, data: Vec<u8>) -> Result {
             let sender = ensure_signed(origin)?;
             ensure!(data.len() <= 256, "listing data cannot be more than 256 bytes");
 
@@ -113,6 +237,7 @@ decl_module! {
                 when: now,
                 vote_ends: vote_exp,
                 concluded: false,
+                threshold,
             };
 
             Self::mint_vote(sender, new_vote, vote_count_by_sender, new_vote_num)?;
@@ -120,12 +245,17 @@ decl_module! {
             Ok(())
         }
 
-        fn cast_lockvote(origin, reference_index: ReferenceIndex, ballot: Ballot, deposit: BalanceOf<T>, duration: T::BlockNumber) -> Result {
+        fn cast_lockvote(origin, reference_index: ReferenceIndex, ballot: Ballot, deposit: BalanceOf<T>, conviction: Conviction) -> Result {
             let sender = ensure_signed(origin)?;
             let vote = Self::votes(&reference_index);
             let now = <system::Module<T>>::block_number();
             let lock_id: LockIdentifier = reference_index.to_be_bytes();
             let current_blocknumber = <system::Module<T>>::block_number();
+            // `None` has no lock period, so it can never satisfy the vote_ends check below.
+            ensure!(conviction != Conviction::None, "LockVote requires a non-None conviction level.");
+            // duration is forced off the conviction level: LockPeriod * 2^(c-1).
+            let periods: T::BlockNumber = conviction.lock_periods().saturated_into();
+            let duration = T::LockPeriod::get() * periods;
             // duration should be at least vote_end
             // deposit should be smaller than freebalance
             ensure!(now + duration >= vote.vote_ends, "Lock duration should be or bigger than vote expiry.");
@@ -135,12 +265,13 @@ decl_module! {
             ensure!(vote.creator != sender, "You cannot vote your own vote.");
             ensure!(vote.vote_ends > now, "This vote has already been expired.");
             ensure!(vote.vote_type == 1, "This vote is not LockVote.");
-            
+
             // lock function
             <LockBalance<T>>::mutate((&reference_index, &sender), |lockinfo| {
                 lockinfo.deposit += deposit;
                 lockinfo.duration = duration;
                 lockinfo.until = current_blocknumber + duration;
+                lockinfo.conviction = conviction;
             });
             T::Currency::set_lock(
                 lock_id,
@@ -175,9 +306,52 @@ decl_module! {
             <LockBalance<T>>::remove((&reference_index, &sender));
             print("Locked token is withdrawn!");
             Self::deposit_event(RawEvent::Withdrew(sender, reference_index));
-    
+
             Ok(())
         }
+
+        // Delegation
+        // Locks `balance` and forwards the sender's weight to `to`'s ballot on every vote
+        // `to` casts directly, until `undelegate` is called. On lock votes that weight is
+        // conviction-scaled; on type-0 votes it is a single headcount.
+        fn delegate(origin, to: T::AccountId, conviction: Conviction, balance: BalanceOf<T>) -> Result {
+            let sender = ensure_signed(origin)?;
+            ensure!(sender != to, "You cannot delegate to yourself.");
+            ensure!(!<Delegations<T>>::exists(&sender), "You are already delegating. Undelegate first.");
+            ensure!(T::Currency::free_balance(&sender) > balance, "You cannot delegate more than your free balance!");
+
+            T::Currency::set_lock(
+                DELEGATE_LOCK_ID,
+                &sender,
+                balance,
+                T::BlockNumber::max_value(),   // held until undelegate removes it
+                WithdrawReasons::except(WithdrawReason::TransactionPayment),
+            );
+            <Delegations<T>>::insert(&sender, (to.clone(), conviction, balance));
+            <Delegators<T>>::mutate(&to, |delegators| delegators.push(sender.clone()));
+            // a delegator contributes a headcount unit to type-0 tallies just like a direct
+            // voter does, so they must join the same electorate total_voter_count measures
+            Self::register_voter(&sender);
+            Self::deposit_event(RawEvent::Delegated(sender, to));
+            Ok(())
+        }
+
+        // Undoes a standing delegation and releases the locked balance.
+        fn undelegate(origin) -> Result {
+            let sender = ensure_signed(origin)?;
+            ensure!(<Delegations<T>>::exists(&sender), "You are not currently delegating.");
+            let (to, _, _) = <Delegations<T>>::get(&sender);
+            T::Currency::remove_lock(DELEGATE_LOCK_ID, &sender);
+            <Delegations<T>>::remove(&sender);
+            <Delegators<T>>::mutate(&to, |delegators| {
+                if let Some(i) = delegators.iter().position(|x| x == &sender) {
+                    delegators.remove(i);
+                }
+            });
+            Self::deposit_event(RawEvent::Undelegated(sender));
+            Ok(())
+        }
+
         // Voter modules
         // cast_ballot checks
             // a. the vote exists
@@ -191,8 +365,10 @@ decl_module! {
             ensure!(vote.creator != sender, "You cannot vote your own vote.");
             ensure!(vote.vote_ends > now, "This vote has already been expired.");
             ensure!(vote.vote_type == 0, "This vote is LockVote. Use 'cast_lockvote' instead!");
+            Self::register_voter(&sender);
             let mut accounts_aye = <VotedAccounts<T>>::get((reference_index, 0));
             let mut accounts_nay = <VotedAccounts<T>>::get((reference_index, 1));
+            Self::guard_flip(reference_index, &sender, ballot, &accounts_aye, &accounts_nay)?;
             // keep track of voter's id in aye or nay vector in Vote
             // Voter can change his vote b/w aye and nay
             // Voter cannot vote twice
@@ -203,7 +379,7 @@ decl_module! {
                     if accounts_nay.contains(&sender) {
                         let i = accounts_nay.iter().position(|x| x == &sender).unwrap() as usize;
                         accounts_nay.remove(i);
-                    } 
+                    }
                     accounts_aye.push(sender.clone());
                     <VotedAccounts<T>>::insert((reference_index, 0), accounts_aye);
                     print("Ballot casted Aye!");
@@ -213,7 +389,7 @@ decl_module! {
                     if accounts_aye.contains(&sender) {
                         let i = accounts_aye.iter().position(|x| x == &sender).unwrap() as usize;
                         accounts_aye.remove(i);
-                    } 
+                    }
                     accounts_nay.push(sender.clone());
                     <VotedAccounts<T>>::insert((reference_index, 1), accounts_nay);
                     print("Ballot casted Nay!");
@@ -232,14 +408,38 @@ decl_module! {
             let now = <system::Module<T>>::block_number();
             // double check
             ensure!(now > vote.vote_ends, "This vote hasn't been expired yet.");
-            Self::tally(reference_index)?;
+            let (ayes, nays) = Self::tally(reference_index)?;
+            let electorate: u128 = match vote.vote_type {
+                0 => Self::total_voter_count() as u128,
+                _ => T::Currency::total_issuance().saturated_into::<u128>(),
+            };
+            let approved = Self::vote_passed(vote.threshold, ayes, nays, electorate);
+            for account in <VotedAccounts<T>>::get((reference_index, 0)) {
+                Self::record_credit(&account, now);
+            }
+            for account in <VotedAccounts<T>>::get((reference_index, 1)) {
+                Self::record_credit(&account, now);
+            }
             // For some reason Storage is not reflected, but works.
             <VotesByIndex<T>>::mutate(&reference_index, |vote| vote.concluded = true);
             <VoteByCreatorArray<T>>::mutate((vote.creator, &reference_index), |vote| vote.concluded = true);
-            Self::deposit_event(RawEvent::Concluded(reference_index));
+            Self::deposit_event(RawEvent::Concluded(reference_index, ayes, nays, approved));
             print("Vote concluded.");
             Ok(())
         }
+
+        // Pays out from the reward pot proportional to accumulated participation
+        // credits, then prunes the claimed history.
+        pub fn claim_rewards(origin) -> Result {
+            let sender = ensure_signed(origin)?;
+            let credits = Self::total_credits(&sender);
+            ensure!(credits > 0, "You have no participation credits to claim.");
+            let reward = T::CreditRewardRate::get() * credits.saturated_into::<BalanceOf<T>>();
+            T::Currency::transfer(&T::RewardPot::get(), &sender, reward)?;
+            <VoterCredits<T>>::remove(&sender);
+            Self::deposit_event(RawEvent::RewardsClaimed(sender, reward));
+            Ok(())
+        }
     }
 }
 
@@ -247,8 +447,10 @@ impl<T: Trait> Module<T> {
     // keep track of accounts in array by Aye/Nay in <VotedAccounts<T>>
     // TODO: lockvote_tally should check <LockBalance> for accuracy
     fn cast_ballot_f(sender: T::AccountId, reference_index: ReferenceIndex, ballot: Ballot) -> Result {
+        Self::register_voter(&sender);
         let mut accounts_aye = <VotedAccounts<T>>::get((reference_index, 0));
         let mut accounts_nay = <VotedAccounts<T>>::get((reference_index, 1));
+        Self::guard_flip(reference_index, &sender, ballot, &accounts_aye, &accounts_nay)?;
         // keep track of voter's id in aye or nay vector in Vote
         // Voter can change his vote b/w aye and nay
         // Voter cannot vote twice
@@ -292,29 +494,156 @@ impl<T: Trait> Module<T> {
         Ok(())
     }
 
+    // conviction-weighted power of a balance: balance * c, balance / 10 for `None`.
+    fn conviction_weight_of(balance: BalanceOf<T>, conviction: Conviction) -> u64 {
+        let balance = balance.saturated_into::<u64>();
+        match conviction {
+            Conviction::None => balance / 10,
+            c => balance.saturating_mul(c.weight_multiplier()),
+        }
+    }
+
+    // conviction-weighted power of a single lock vote
+    fn conviction_weight(lock_vote: &LockInfo<BalanceOf<T>, T::BlockNumber>) -> u64 {
+        Self::conviction_weight_of(lock_vote.deposit, lock_vote.conviction)
+    }
+
+    // Delegators standing behind `delegate` who are resolved into `delegate`'s own
+    // ballot this referendum: one level of redirection, and only those who haven't
+    // voted directly themselves (a direct ballot always overrides a delegation).
+    fn eligible_delegators(delegate: &T::AccountId, ayes: &[T::AccountId], nays: &[T::AccountId]) -> Vec<T::AccountId> {
+        <Delegators<T>>::get(delegate).into_iter()
+            .filter(|delegator| !ayes.contains(delegator) && !nays.contains(delegator))
+            .collect()
+    }
+
+    // Type-0 (unweighted) votes are one-account-one-vote; a delegator must only ever
+    // be worth the same single unit a direct voter is, regardless of balance/conviction.
+    fn inbound_delegated_headcount(delegate: &T::AccountId, ayes: &[T::AccountId], nays: &[T::AccountId]) -> u64 {
+        Self::eligible_delegators(delegate, ayes, nays).len() as u64
+    }
+
+    // Type-1 (lockvote) votes are already balance-weighted, so delegated stake carries
+    // its conviction-scaled weight same as a direct lockvote deposit would.
+    fn inbound_delegated_stake(delegate: &T::AccountId, ayes: &[T::AccountId], nays: &[T::AccountId]) -> u64 {
+        Self::eligible_delegators(delegate, ayes, nays).iter()
+            .map(|delegator| {
+                let (_, conviction, balance) = <Delegations<T>>::get(delegator);
+                Self::conviction_weight_of(balance, conviction)
+            })
+            .fold(0u64, |acc, weight| acc.saturating_add(weight))
+    }
+
+    // first-time voters join the type-0 electorate
+    fn register_voter(sender: &T::AccountId) {
+        if !<KnownVoters<T>>::exists(sender) {
+            <KnownVoters<T>>::insert(sender, true);
+            <TotalVoterCount>::mutate(|count| *count += 1);
+        }
+    }
+
+    // accrue one credit for `account` at block `now`, trimming the oldest
+    // history entry once T::MaxCreditHistory is exceeded
+    fn record_credit(account: &T::AccountId, now: T::BlockNumber) {
+        <VoterCredits<T>>::mutate(account, |history| {
+            if let Some(last) = history.last_mut() {
+                if last.0 == now {
+                    last.1 += 1;
+                    return;
+                }
+            }
+            history.push((now, 1));
+            if history.len() > T::MaxCreditHistory::get() {
+                history.remove(0);
+            }
+        });
+    }
+
+    fn total_credits(account: &T::AccountId) -> u64 {
+        Self::credits_of(account).iter().map(|(_, credits)| *credits).sum()
+    }
+
+    // Rejects a ballot flip (voting the other way than currently recorded) before
+    // `locked_until`; otherwise records the flip with a geometrically longer lockout,
+    // mirroring Solana's Lockout expiration = slot + INITIAL_LOCKOUT.pow(confirmations).
+    // A voter's first ballot is never a flip and is always free.
+    fn guard_flip(reference_index: ReferenceIndex, sender: &T::AccountId, ballot: Ballot, accounts_aye: &[T::AccountId], accounts_nay: &[T::AccountId]) -> Result {
+        let is_flip = match ballot {
+            Ballot::Aye => accounts_nay.contains(sender),
+            Ballot::Nay => accounts_aye.contains(sender),
+        };
+        if !is_flip {
+            return Ok(());
+        }
+        let now = <system::Module<T>>::block_number();
+        let lock = <BallotLockout<T>>::get((reference_index, sender));
+        ensure!(now >= lock.locked_until, "You must wait before flipping your ballot again.");
+        let lockout = T::BaseFlipLockout::get().saturating_mul(Self::flip_multiplier(lock.flip_count));
+        <BallotLockout<T>>::insert((reference_index, sender.clone()), FlipLock {
+            flip_count: lock.flip_count + 1,
+            locked_until: now.saturating_add(lockout),
+        });
+        Ok(())
+    }
+
+    fn flip_multiplier(flip_count: u32) -> T::BlockNumber {
+        1u64.checked_shl(flip_count).unwrap_or(u64::max_value()).saturated_into()
+    }
+
+    // turnout-sensitive passage check: relaxes from supermajority toward
+    // simple majority as participation (sqrt_voters / sqrt_electorate) grows.
+    fn vote_passed(threshold: VoteThreshold, ayes: u64, nays: u64, electorate: u128) -> bool {
+        let ayes = ayes as u128;
+        let nays = nays as u128;
+        let sqrt_voters = isqrt(ayes + nays);
+        let sqrt_electorate = isqrt(electorate);
+        if sqrt_voters == 0 {
+            return false;
+        }
+        match threshold {
+            VoteThreshold::SuperMajorityApprove =>
+                compare_rationals(nays, sqrt_voters, ayes, sqrt_electorate),
+            VoteThreshold::SuperMajorityAgainst =>
+                compare_rationals(nays, sqrt_electorate, ayes, sqrt_voters),
+            VoteThreshold::SimpleMajority => ayes > nays,
+        }
+    }
+
     // only called after the vote expired
-    fn tally(reference_index: u64) -> Result {
+    fn tally(reference_index: u64) -> core::result::Result<(u64, u64), &'static str> {
         let vote = Self::votes(reference_index);
         let mut aye_count: u64 = 0;
         let mut nay_count: u64 = 0;
         match vote.vote_type {
-            // normal vote tally
+            // normal vote tally: one vote per direct voter, plus the delegated
+            // weight of anyone standing delegated to them who didn't vote themselves
             0 => {
-                aye_count = <VotedAccounts<T>>::get((reference_index, 0)).len() as u64;
-                nay_count = <VotedAccounts<T>>::get((reference_index, 1)).len() as u64;
+                let ayes = <VotedAccounts<T>>::get((reference_index, 0));
+                let nays = <VotedAccounts<T>>::get((reference_index, 1));
+                aye_count = ayes.len() as u64;
+                nay_count = nays.len() as u64;
+                for account in ayes.iter() {
+                    aye_count += Self::inbound_delegated_headcount(account, &ayes, &nays);
+                }
+                for account in nays.iter() {
+                    nay_count += Self::inbound_delegated_headcount(account, &ayes, &nays);
+                }
             }
             // lock vote tally
-            // deposit amount * duration
+            // conviction-weighted: deposit * conviction multiplier (deposit / 10 for `None`),
+            // plus inbound delegated stake for anyone who voted directly
             1 => {
-                for account in <VotedAccounts<T>>::get((reference_index, 0)) {
-                    let lock_vote = <LockBalance<T>>::get((reference_index, account));
-                    let vote_power: u64 = lock_vote.deposit.saturated_into::<u64>() * lock_vote.duration.saturated_into::<u64>();
-                    aye_count += vote_power;
+                let ayes = <VotedAccounts<T>>::get((reference_index, 0));
+                let nays = <VotedAccounts<T>>::get((reference_index, 1));
+                for account in ayes.iter() {
+                    let lock_vote = <LockBalance<T>>::get((reference_index, account.clone()));
+                    aye_count += Self::conviction_weight(&lock_vote);
+                    aye_count += Self::inbound_delegated_stake(account, &ayes, &nays);
                 }
-                for account in <VotedAccounts<T>>::get((reference_index, 0)) {
-                    let lock_vote = <LockBalance<T>>::get((reference_index, account));
-                    let vote_power: u64 = lock_vote.deposit.saturated_into::<u64>() * lock_vote.duration.saturated_into::<u64>();
-                    nay_count += vote_power;
+                for account in nays.iter() {
+                    let lock_vote = <LockBalance<T>>::get((reference_index, account.clone()));
+                    nay_count += Self::conviction_weight(&lock_vote);
+                    nay_count += Self::inbound_delegated_stake(account, &ayes, &nays);
                 }
             }
             _ => ensure!(vote.vote_type <= 1, "This vote_type is not covered."),
@@ -323,7 +652,7 @@ impl<T: Trait> Module<T> {
         result.push(aye_count);
         result.push(nay_count);
         <VoteResults>::insert(reference_index, result);
-        Ok(())
+        Ok((aye_count, nay_count))
     }
 }
 
@@ -365,6 +694,10 @@ mod tests {
         type Event = ();
         type Currency = balances::Module<Test>;
         type LockPeriod = BlockHashCount;
+        type BaseFlipLockout = BaseFlipLockout;
+        type MaxCreditHistory = MaxCreditHistory;
+        type RewardPot = RewardPot;
+        type CreditRewardRate = CreditRewardRate;
     }
     parameter_types! {
         pub const BlockHashCount: u64 = 250;
@@ -375,6 +708,10 @@ mod tests {
         pub const ExistentialDeposit: u64 = 0;
 		pub const TransferFee: u64 = 0;
         pub const CreationFee: u64 = 0;
+        pub const BaseFlipLockout: u64 = 10;
+        pub const MaxCreditHistory: usize = 50;
+        pub const RewardPot: u64 = 0;
+        pub const CreditRewardRate: u64 = 1;
     }
 
 
@@ -436,7 +773,136 @@ mod tests {
             assert!(true);
         });
     }
-    
+
+    #[test]
+    fn isqrt_computes_integer_square_roots() {
+        assert_eq!(isqrt(0), 0);
+        assert_eq!(isqrt(1), 1);
+        assert_eq!(isqrt(15), 3);
+        assert_eq!(isqrt(16), 4);
+        assert_eq!(isqrt(1_000_000), 1000);
+    }
+
+    #[test]
+    fn compare_rationals_orders_correctly_and_guards_zero_denominators() {
+        assert!(compare_rationals(1, 2, 2, 3)); // 1/2 < 2/3
+        assert!(!compare_rationals(2, 3, 1, 2)); // 2/3 < 1/2 is false
+        assert!(!compare_rationals(1, 0, 1, 1));
+        assert!(!compare_rationals(1, 1, 1, 0));
+    }
+
+    #[test]
+    fn vote_passed_simple_majority_is_just_ayes_greater_than_nays() {
+        assert!(Governance::vote_passed(VoteThreshold::SimpleMajority, 6, 5, 100));
+        assert!(!Governance::vote_passed(VoteThreshold::SimpleMajority, 5, 6, 100));
+    }
+
+    #[test]
+    fn vote_passed_super_majority_approve_relaxes_as_turnout_grows() {
+        // same 6-aye/4-nay split: low turnout out of a huge electorate needs a real supermajority
+        assert!(!Governance::vote_passed(VoteThreshold::SuperMajorityApprove, 6, 4, 1_000_000));
+        // full turnout (electorate == ayes + nays): relaxes to simple majority
+        assert!(Governance::vote_passed(VoteThreshold::SuperMajorityApprove, 6, 4, 10));
+    }
+
+    #[test]
+    fn vote_passed_zero_voters_never_approved() {
+        assert!(!Governance::vote_passed(VoteThreshold::SimpleMajority, 0, 0, 100));
+    }
+
+    #[test]
+    fn conviction_levels_scale_lock_period_and_weight() {
+        assert_eq!(Conviction::None.lock_periods(), 0);
+        assert_eq!(Conviction::Locked1x.lock_periods(), 1);
+        assert_eq!(Conviction::Locked6x.lock_periods(), 32);
+        assert_eq!(Conviction::None.weight_multiplier(), 0);
+        assert_eq!(Conviction::Locked6x.weight_multiplier(), 6);
+    }
+
+    #[test]
+    fn conviction_weight_of_saturates_instead_of_overflowing() {
+        let huge = u64::max_value();
+        assert_eq!(Governance::conviction_weight_of(huge, Conviction::Locked6x), u64::max_value());
+    }
+
+    #[test]
+    fn record_credit_merges_same_block_and_trims_oldest() {
+        TestExternalities::default().execute_with(|| {
+            let account = 1u64;
+            let cap = MaxCreditHistory::get() as u64;
+            for block in 0..(cap + 5) {
+                Governance::record_credit(&account, block);
+            }
+            let history = Governance::credits_of(&account);
+            assert_eq!(history.len(), cap as usize);
+            // the oldest 5 entries (blocks 0..4) should have been trimmed
+            assert_eq!(history[0].0, 5);
+
+            let before_len = history.len();
+            let last_block = history.last().unwrap().0;
+            Governance::record_credit(&account, last_block);
+            let after = Governance::credits_of(&account);
+            assert_eq!(after.len(), before_len);
+            assert_eq!(after.last().unwrap().1, 2);
+        });
+    }
+
+    #[test]
+    fn guard_flip_allows_first_vote_then_enforces_geometric_lockout() {
+        TestExternalities::default().execute_with(|| {
+            let sender = 1u64;
+            let reference_index: ReferenceIndex = 7;
+            let empty: Vec<u64> = Vec::new();
+
+            // first ballot: not recorded on either side yet, so it's never a flip
+            assert_ok!(Governance::guard_flip(reference_index, &sender, Ballot::Aye, &empty, &empty));
+
+            // flipping Nay -> Aye is the voter's first real flip: allowed, and it starts the lockout
+            let nays = vec![sender];
+            assert_ok!(Governance::guard_flip(reference_index, &sender, Ballot::Aye, &empty, &nays));
+
+            // first flip at block 0 locks for BaseFlipLockout * 2^0 = 10 blocks
+            assert_eq!(<BallotLockout<Test>>::get((reference_index, sender)).locked_until, 10);
+
+            // flipping straight back before the lockout elapses is rejected
+            let ayes = vec![sender];
+            assert_eq!(
+                Governance::guard_flip(reference_index, &sender, Ballot::Nay, &ayes, &empty),
+                Err("You must wait before flipping your ballot again.")
+            );
+
+            // once it elapses the second flip is allowed and locks for 10 * 2^1 = 20 blocks
+            System::set_block_number(10);
+            assert_ok!(Governance::guard_flip(reference_index, &sender, Ballot::Nay, &ayes, &empty));
+            assert_eq!(<BallotLockout<Test>>::get((reference_index, sender)).locked_until, 30);
+        });
+    }
+
+    #[test]
+    fn inbound_delegated_headcount_counts_unvoted_delegators_and_respects_override() {
+        TestExternalities::default().execute_with(|| {
+            let delegate = 1u64;
+            let delegator_a = 2u64;
+            let delegator_b = 3u64;
+            <Delegators<Test>>::mutate(&delegate, |delegators| {
+                delegators.push(delegator_a);
+                delegators.push(delegator_b);
+            });
+            <Delegations<Test>>::insert(&delegator_a, (delegate, Conviction::Locked6x, 1_000_000u64));
+            <Delegations<Test>>::insert(&delegator_b, (delegate, Conviction::Locked6x, 1_000_000u64));
+
+            let ayes = vec![delegate];
+            let nays: Vec<u64> = Vec::new();
+            // both delegators inactive: headcount is 2 regardless of their huge delegated balance
+            assert_eq!(Governance::inbound_delegated_headcount(&delegate, &ayes, &nays), 2);
+
+            // delegator_b casts their own ballot directly: that overrides their delegation
+            let ayes_with_direct_voter = vec![delegate, delegator_b];
+            assert_eq!(Governance::inbound_delegated_headcount(&delegate, &ayes_with_direct_voter, &nays), 1);
+        });
+    }
+
+
 	// #[test]
 	// fn vote_creation() {
     //     with_externalities(&mut new_test_ext(), || {